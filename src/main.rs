@@ -1,35 +1,516 @@
+use std::env;
+use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
 use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 use termios::*;
 
 static EDITEUREN_VERSION: &str = "11";
+const TAB_STOP: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditorKey {
+  ArrowUp,
+  ArrowDown,
+  ArrowLeft,
+  ArrowRight,
+  PageUp,
+  PageDown,
+  Home,
+  End,
+  Delete,
+  Escape,
+  Char(char)
+}
 
 struct Keyboard {
-  stdin: io::Stdin
+  stdin: io::Stdin,
+  // Checked between read attempts so the background input thread can
+  // be asked to stop without a keypress ever arriving.
+  shutdown: Arc<AtomicBool>
 }
 
 impl Keyboard {
-  fn new() -> Keyboard {
-    Keyboard { stdin: io::stdin() }
+  fn new(shutdown: Arc<AtomicBool>) -> Keyboard {
+    Keyboard { stdin: io::stdin(), shutdown }
   }
 
   fn ctrl_key(c: char) -> char {
     (c as u8 & 0x1fu8) as char
   }
 
-  fn read_key(&mut self) -> char {
+  // Retries across the VTIME timeout window until a byte arrives or
+  // shutdown is requested, in which case it gives up and returns None.
+  fn read_raw_byte(&mut self) -> Option<u8> {
     let mut buf: [u8; 1] = [0];
-  
+
     loop {
+      if self.shutdown.load(Ordering::Relaxed) {
+        return None;
+      }
+
       match self.stdin.read(&mut buf) {
-        Ok(1)  => break buf[0] as char,
+        Ok(1)  => return Some(buf[0]),
         Ok(_)  => (),
         Err(e) => panic!("Failed because: {}", e)
       }
     }
   }
+
+  // A single, non-retrying read within the VTIME window. Used while
+  // decoding an escape sequence, where "nothing arrived" is meaningful
+  // (a bare Escape keypress) rather than something to keep waiting on.
+  fn try_read_byte(&mut self) -> Option<u8> {
+    let mut buf: [u8; 1] = [0];
+
+    match self.stdin.read(&mut buf) {
+      Ok(1)  => Some(buf[0]),
+      Ok(_)  => None,
+      Err(e) => panic!("Failed because: {}", e)
+    }
+  }
+
+  // Returns None only when shutdown was requested while waiting for
+  // the first byte of a key; every other outcome is a decoded key.
+  fn read_key(&mut self) -> Option<EditorKey> {
+    let c = self.read_raw_byte()?;
+
+    if c != 0x1b {
+      return Some(EditorKey::Char(c as char));
+    }
+
+    let b1 = match self.try_read_byte() {
+      Some(b) => b,
+      None    => return Some(EditorKey::Escape)
+    };
+    let b2 = match self.try_read_byte() {
+      Some(b) => b,
+      None    => return Some(EditorKey::Escape)
+    };
+
+    if b1 != b'[' {
+      return Some(EditorKey::Escape);
+    }
+
+    let key = if b2.is_ascii_digit() {
+      let b3 = match self.try_read_byte() {
+        Some(b) => b,
+        None    => return Some(EditorKey::Escape)
+      };
+
+      if b3 != b'~' {
+        return Some(EditorKey::Escape);
+      }
+
+      match b2 {
+        b'1' | b'7' => EditorKey::Home,
+        b'4' | b'8' => EditorKey::End,
+        b'3'        => EditorKey::Delete,
+        b'5'        => EditorKey::PageUp,
+        b'6'        => EditorKey::PageDown,
+        _           => EditorKey::Escape
+      }
+    } else {
+      match b2 {
+        b'A' => EditorKey::ArrowUp,
+        b'B' => EditorKey::ArrowDown,
+        b'C' => EditorKey::ArrowRight,
+        b'D' => EditorKey::ArrowLeft,
+        b'H' => EditorKey::Home,
+        b'F' => EditorKey::End,
+        _    => EditorKey::Escape
+      }
+    };
+
+    Some(key)
+  }
+}
+
+// Runs `Keyboard::read_key` on a dedicated thread and funnels decoded
+// keys through a channel, so the main loop can poll for input with
+// `try_recv`/`recv_timeout` instead of blocking on the keyboard. That
+// lets the main loop refresh on its own timer -- for the message bar's
+// timed expiry, and for any future async work -- independent of when
+// the next keystroke shows up.
+struct InputThread {
+  receiver: mpsc::Receiver<EditorKey>,
+  shutdown: Arc<AtomicBool>,
+  handle: Option<thread::JoinHandle<()>>
+}
+
+impl InputThread {
+  fn spawn() -> Self {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let thread_shutdown = Arc::clone(&shutdown);
+    let (sender, receiver) = mpsc::channel();
+
+    let handle = thread::spawn(move || {
+      let mut keyboard = Keyboard::new(thread_shutdown);
+
+      while let Some(key) = keyboard.read_key() {
+        if sender.send(key).is_err() {
+          break;
+        }
+      }
+    });
+
+    InputThread { receiver, shutdown, handle: Some(handle) }
+  }
+
+  fn recv_timeout(&self, timeout: Duration) -> Option<EditorKey> {
+    self.receiver.recv_timeout(timeout).ok()
+  }
+
+  fn recv(&self) -> Option<EditorKey> {
+    self.receiver.recv().ok()
+  }
+
+  // Signals the thread to stop and waits for it to notice, so the
+  // terminal isn't restored out from under a still-reading thread.
+  fn shutdown(&mut self) {
+    self.shutdown.store(true, Ordering::Relaxed);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+struct EditorRow {
+  chars: String,
+  render: String
+}
+
+impl EditorRow {
+  fn new(chars: &str) -> Self {
+    let chars = chars.to_string();
+    let render = EditorRow::expand_tabs(&chars);
+    EditorRow { chars, render }
+  }
+
+  // Expands tabs into spaces up to the next TAB_STOP boundary, the way
+  // the rest of the render pipeline expects a fixed-width column grid.
+  fn expand_tabs(chars: &str) -> String {
+    let mut render = String::new();
+    let mut render_x = 0;
+
+    for c in chars.chars() {
+      if c == '\t' {
+        render.push(' ');
+        render_x += 1;
+        while !render_x.is_multiple_of(TAB_STOP) {
+          render.push(' ');
+          render_x += 1;
+        }
+      } else {
+        render.push(c);
+        render_x += 1;
+      }
+    }
+
+    render
+  }
+
+  // Maps a cursor column in `chars` space to the corresponding column
+  // in `render` space, accounting for tab expansion along the way.
+  fn chars_to_render_x(chars: &str, cursor_x: usize) -> usize {
+    let mut render_x = 0;
+
+    for c in chars.chars().take(cursor_x) {
+      if c == '\t' {
+        render_x += TAB_STOP - 1 - (render_x % TAB_STOP);
+      }
+      render_x += 1;
+    }
+
+    render_x
+  }
+
+  // The inverse of `chars_to_render_x`: maps a column in `render` space
+  // back to the `chars`-space index it was expanded from.
+  fn render_x_to_chars_x(chars: &str, render_x: usize) -> usize {
+    let mut cur_render_x = 0;
+
+    for (i, c) in chars.chars().enumerate() {
+      if c == '\t' {
+        cur_render_x += TAB_STOP - 1 - (cur_render_x % TAB_STOP);
+      }
+      cur_render_x += 1;
+
+      if cur_render_x > render_x {
+        return i;
+      }
+    }
+
+    chars.chars().count()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PieceSource {
+  Original,
+  Add
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Piece {
+  source: PieceSource,
+  start: usize,
+  len: usize
+}
+
+// A piece-table document buffer: `original` is the immutable bytes the
+// file was opened with, `add` is an append-only scratch buffer for
+// newly typed text, and `pieces` stitches slices of the two back
+// together in document order. Edits only ever touch `pieces` and
+// append to `add`, so insertion/deletion cost is proportional to the
+// number of pieces rather than the size of the document.
+struct PieceTable {
+  original: Vec<u8>,
+  add: Vec<u8>,
+  pieces: Vec<Piece>
+}
+
+// Several of these are foundation for upcoming editing commands (and,
+// eventually, undo via snapshotting `pieces`) and aren't wired up to a
+// keybinding yet.
+#[allow(dead_code)]
+impl PieceTable {
+  fn new(original: Vec<u8>) -> Self {
+    let len = original.len();
+    let pieces = if len == 0 {
+      Vec::new()
+    } else {
+      vec![Piece { source: PieceSource::Original, start: 0, len }]
+    };
+
+    PieceTable { original, add: Vec::new(), pieces }
+  }
+
+  fn len(&self) -> usize {
+    self.pieces.iter().map(|piece| piece.len).sum()
+  }
+
+  fn source_bytes(&self, source: PieceSource) -> &[u8] {
+    match source {
+      PieceSource::Original => &self.original,
+      PieceSource::Add => &self.add
+    }
+  }
+
+  // Walks the piece list, yielding the logical byte sequence the
+  // pieces describe. This is the only place that needs to know how to
+  // stitch `original` and `add` back together.
+  fn iter_bytes(&self) -> impl Iterator<Item = u8> + '_ {
+    self.pieces.iter().flat_map(move |piece| {
+      self.source_bytes(piece.source)[piece.start..piece.start + piece.len].iter().copied()
+    })
+  }
+
+  fn to_text(&self) -> String {
+    String::from_utf8(self.iter_bytes().collect()).unwrap_or_default()
+  }
+
+  // Inserts `text` at the logical byte offset `at`, splitting the
+  // piece that spans it into up to three pieces: the untouched head,
+  // a new Add-sourced piece for `text`, and the untouched tail.
+  fn insert(&mut self, at: usize, text: &str) {
+    if text.is_empty() {
+      return;
+    }
+
+    let add_start = self.add.len();
+    self.add.extend_from_slice(text.as_bytes());
+    let new_piece = Piece { source: PieceSource::Add, start: add_start, len: text.len() };
+
+    let mut offset = 0;
+    for i in 0..self.pieces.len() {
+      let piece = self.pieces[i];
+
+      if at < offset || at > offset + piece.len {
+        offset += piece.len;
+        continue;
+      }
+
+      if at == offset + piece.len {
+        self.pieces.insert(i + 1, new_piece);
+        return;
+      }
+
+      let head_len = at - offset;
+      let head = Piece { source: piece.source, start: piece.start, len: head_len };
+      let tail = Piece { source: piece.source, start: piece.start + head_len, len: piece.len - head_len };
+
+      let replacement: Vec<Piece> = vec![head, new_piece, tail].into_iter().filter(|p| p.len > 0).collect();
+      self.pieces.splice(i..=i, replacement);
+      return;
+    }
+
+    // Past the end of every existing piece (including an empty table).
+    self.pieces.push(new_piece);
+  }
+
+  // Deletes `len` logical bytes starting at `at`, trimming or removing
+  // every piece the range covers.
+  fn delete(&mut self, at: usize, len: usize) {
+    if len == 0 {
+      return;
+    }
+
+    let end = at + len;
+    let mut offset = 0;
+    let mut result = Vec::with_capacity(self.pieces.len());
+
+    for piece in &self.pieces {
+      let piece_start = offset;
+      let piece_end = offset + piece.len;
+      offset = piece_end;
+
+      if piece_end <= at || piece_start >= end {
+        result.push(*piece);
+        continue;
+      }
+
+      if piece_start < at {
+        result.push(Piece { source: piece.source, start: piece.start, len: at - piece_start });
+      }
+      if piece_end > end {
+        let trimmed_start = piece.start + (end - piece_start);
+        result.push(Piece { source: piece.source, start: trimmed_start, len: piece_end - end });
+      }
+    }
+
+    self.pieces = result;
+  }
+
+  // Maps (row, col) cursor coordinates -- both zero-based, `col` in
+  // chars -- to a global logical byte offset, so a cursor position can
+  // be turned into an edit point for `insert`/`delete`.
+  fn offset_for_cursor(&self, row: usize, col: usize) -> usize {
+    let text = self.to_text();
+    let mut offset = 0;
+
+    for (i, line) in text.split('\n').enumerate() {
+      if i == row {
+        let target_chars = col.min(line.chars().count());
+        let byte_len: usize = line.chars().take(target_chars).map(|c| c.len_utf8()).sum();
+        return offset + byte_len;
+      }
+      offset += line.len() + 1;
+    }
+
+    offset
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HexPane {
+  Hex,
+  Ascii
+}
+
+// The document model behind hex mode: a flat byte buffer, a cursor
+// expressed as a byte offset, which of the two panes (hex digits or
+// ASCII gutter) currently has focus, and which nibble of the
+// highlighted byte a hex keystroke would land on next. Edits only
+// touch `bytes`; nothing reaches disk until `save` is called
+// explicitly, so navigating and typing around a file can't corrupt it.
+struct HexDocument {
+  bytes: Vec<u8>,
+  path: Option<String>,
+  writable: bool,
+  cursor: usize,
+  pane: HexPane,
+  nibble: u8,
+  dirty: u32
+}
+
+impl HexDocument {
+  const BYTES_PER_ROW: usize = 16;
+
+  fn open(path: &str) -> io::Result<Self> {
+    // A file without write permission can still be viewed in hex mode;
+    // only fall back to read-only once a writable open is refused.
+    let (mut file, writable) = match fs::OpenOptions::new().read(true).write(true).open(path) {
+      Ok(file) => (file, true),
+      Err(_)   => (fs::File::open(path)?, false)
+    };
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    Ok(HexDocument {
+      bytes,
+      path: Some(path.to_string()),
+      writable,
+      cursor: 0,
+      pane: HexPane::Hex,
+      nibble: 0,
+      dirty: 0
+    })
+  }
+
+  fn empty() -> Self {
+    HexDocument { bytes: Vec::new(), path: None, writable: true, cursor: 0, pane: HexPane::Hex, nibble: 0, dirty: 0 }
+  }
+
+  fn row_count(&self) -> usize {
+    self.bytes.len().max(1).div_ceil(Self::BYTES_PER_ROW)
+  }
+
+  fn insert_byte(&mut self, at: usize, byte: u8) {
+    let at = at.min(self.bytes.len());
+    self.bytes.insert(at, byte);
+    self.dirty += 1;
+  }
+
+  fn delete_byte(&mut self, at: usize) {
+    if at < self.bytes.len() {
+      self.bytes.remove(at);
+      self.dirty += 1;
+    }
+  }
+
+  fn update_byte(&mut self, at: usize, byte: u8) {
+    if let Some(slot) = self.bytes.get_mut(at) {
+      *slot = byte;
+      self.dirty += 1;
+    }
+  }
+
+  fn set_nibble(&mut self, at: usize, high: bool, digit: u8) {
+    let current = self.bytes.get(at).copied().unwrap_or(0);
+    let updated = if high {
+      (digit << 4) | (current & 0x0f)
+    } else {
+      (current & 0xf0) | digit
+    };
+
+    self.update_byte(at, updated)
+  }
+
+  // Writes the whole in-memory buffer back to `path`, the one point
+  // where a hex edit actually reaches disk.
+  fn save(&mut self) -> io::Result<()> {
+    if !self.writable {
+      return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file is read-only"));
+    }
+
+    let path = match &self.path {
+      Some(path) => path,
+      None       => return Err(io::Error::new(io::ErrorKind::Other, "no file name"))
+    };
+
+    fs::write(path, &self.bytes)?;
+    self.dirty = 0;
+
+    Ok(())
+  }
 }
 
 #[derive(Debug)]
@@ -90,6 +571,14 @@ impl AnsiBuffer {
     self.buffer.push_str("\x1b[?25l")
   }
 
+  fn enter_inverse_video(&mut self) {
+    self.buffer.push_str("\x1b[7m")
+  }
+
+  fn leave_inverse_video(&mut self) {
+    self.buffer.push_str("\x1b[m")
+  }
+
   fn move_cursor_to(&mut self, row: i32, col: i32) {
     let s = format!("\x1b[{};{}H", row + 1, col + 1);
     self.buffer.push_str(&s)
@@ -101,45 +590,109 @@ impl AnsiBuffer {
   }
 }
 
+// What the status bar needs to know, gathered up-front so `Screen`
+// doesn't have to reach back into `Editor` state to render it.
+struct StatusInfo<'a> {
+  filename: Option<&'a str>,
+  total_lines: usize,
+  current_line: usize,
+  modified: bool
+}
+
 struct Screen {
   stdout: io::Stdout,
   width: u32,
   height: u32,
-  cursor: (i32, i32)
+  cursor: (i32, i32),
+  row_offset: i32,
+  col_offset: i32,
+  // Set while an incremental search is in progress: (row, start, end)
+  // in render-space, drawn in inverse video. Cleared once the search ends.
+  highlight: Option<(i32, usize, usize)>
 }
 
 impl Screen {
+  // Two rows at the bottom are reserved for the status bar and the
+  // message bar, so the scrollable text area is shorter than the
+  // full terminal height.
+  const RESERVED_ROWS: u32 = 2;
+
   fn new() -> io::Result<Self> {
     let (width, height) = Winsize::get()?;
-    let screen = 
+    let screen =
       Screen {
-        stdout: io::stdout(), 
+        stdout: io::stdout(),
         width: width as u32,
         height: height as u32,
-        cursor: (0, 0)
+        cursor: (0, 0),
+        row_offset: 0,
+        col_offset: 0,
+        highlight: None
       };
     Ok(screen)
   }
 
-  fn refresh(&mut self) -> io::Result<()> {
+  fn text_height(&self) -> u32 {
+    self.height.saturating_sub(Self::RESERVED_ROWS)
+  }
+
+  fn cursor_render_x(&self, rows: &[EditorRow]) -> i32 {
+    match rows.get(self.cursor.0 as usize) {
+      Some(row) => EditorRow::chars_to_render_x(&row.chars, self.cursor.1 as usize) as i32,
+      None => 0
+    }
+  }
+
+  fn scroll(&mut self, rows: &[EditorRow]) {
+    let render_x = self.cursor_render_x(rows);
+    let text_height = self.text_height() as i32;
+
+    if self.cursor.0 < self.row_offset {
+      self.row_offset = self.cursor.0;
+    }
+    if self.cursor.0 >= self.row_offset + text_height {
+      self.row_offset = self.cursor.0 - text_height + 1;
+    }
+    if render_x < self.col_offset {
+      self.col_offset = render_x;
+    }
+    if render_x >= self.col_offset + self.width as i32 {
+      self.col_offset = render_x - self.width as i32 + 1;
+    }
+  }
+
+  fn refresh(&mut self, rows: &[EditorRow], status: StatusInfo, message: Option<&str>) -> io::Result<()> {
+    self.scroll(rows);
+
     let mut buffer = AnsiBuffer::new();
     buffer.hide_cursor();
     buffer.move_top_left();
-    self.draw_rows(&mut buffer);
+    self.draw_rows(&mut buffer, rows);
+    self.draw_status_bar(&mut buffer, &status);
+    self.draw_message_bar(&mut buffer, message);
     buffer.move_top_left();
-    buffer.move_cursor_to(self.cursor.0, self.cursor.1);
+
+    let render_x = self.cursor_render_x(rows);
+    let screen_row = self.cursor.0 - self.row_offset;
+    let screen_col = render_x - self.col_offset;
+    buffer.move_cursor_to(screen_row, screen_col);
     buffer.show_cursor();
     buffer.emit_and_flush(&mut self.stdout)
   }
 
-  fn update_cursor_location(&mut self, row_delta: i32, col_delta: i32) {
-    self.cursor.0 += row_delta;
-    self.cursor.1 += col_delta;
+  fn draw_rows(&mut self, buffer: &mut AnsiBuffer, rows: &[EditorRow]) {
+    for visual_row in 0..self.text_height() {
+      self.draw_row(buffer, rows, visual_row);
+      buffer.erase_to_end_of_line();
+      buffer.append("\r\n")
+    }
   }
 
-  fn draw_rows(&mut self, buffer: &mut AnsiBuffer) {
-    for i in 1..self.height {
-      if i == self.height / 3 {
+  fn draw_row(&mut self, buffer: &mut AnsiBuffer, rows: &[EditorRow], visual_row: u32) {
+    let file_row = visual_row as i32 + self.row_offset;
+
+    if file_row < 0 || file_row as usize >= rows.len() {
+      if rows.is_empty() && visual_row == self.height / 3 {
         let mut blurb = format!("Editeuren editor -- version {}", EDITEUREN_VERSION);
         blurb.truncate(self.width as usize);
 
@@ -149,38 +702,265 @@ impl Screen {
 
         buffer.append(&padding);
         buffer.append(&blurb);
-        buffer.erase_to_end_of_line();
-        buffer.append("\r\n")
       } else {
         buffer.append("~");
-        buffer.erase_to_end_of_line();
-        buffer.append("\r\n")
+      }
+      return;
+    }
+
+    let render: Vec<char> = rows[file_row as usize].render.chars().collect();
+    let start = (self.col_offset as usize).min(render.len());
+    let end = (start + self.width as usize).min(render.len());
+
+    match self.highlight.filter(|(row, _, _)| *row == file_row) {
+      Some((_, highlight_start, highlight_end)) => {
+        let highlight_start = highlight_start.clamp(start, end);
+        let highlight_end = highlight_end.clamp(highlight_start, end);
+
+        let before: String = render[start..highlight_start].iter().collect();
+        let matched: String = render[highlight_start..highlight_end].iter().collect();
+        let after: String = render[highlight_end..end].iter().collect();
+
+        buffer.append(&before);
+        buffer.enter_inverse_video();
+        buffer.append(&matched);
+        buffer.leave_inverse_video();
+        buffer.append(&after);
+      }
+      None => {
+        let line: String = render[start..end].iter().collect();
+        buffer.append(&line)
       }
     }
+  }
+
+  fn draw_status_bar(&mut self, buffer: &mut AnsiBuffer, status: &StatusInfo) {
+    buffer.enter_inverse_video();
+
+    let name = status.filename.unwrap_or("[No Name]");
+    let modified = if status.modified { " (modified)" } else { "" };
+    let left: String = format!("{}{} - {} lines", name, modified, status.total_lines)
+      .chars()
+      .take(self.width as usize)
+      .collect();
+
+    let right = format!("{}/{}", status.current_line, status.total_lines);
+
+    let width = self.width as usize;
+    let left_len = left.chars().count();
+    let right_len = right.chars().count();
+
+    buffer.append(&left);
+    if left_len + right_len <= width {
+      buffer.append(&" ".repeat(width - left_len - right_len));
+      buffer.append(&right);
+    } else {
+      buffer.append(&" ".repeat(width.saturating_sub(left_len)));
+    }
+
+    buffer.leave_inverse_video();
+    buffer.append("\r\n")
+  }
+
+  fn draw_message_bar(&mut self, buffer: &mut AnsiBuffer, message: Option<&str>) {
     buffer.erase_to_end_of_line();
-    buffer.append("~")
+
+    if let Some(message) = message {
+      let mut text = message.to_string();
+      text.truncate(self.width as usize);
+      buffer.append(&text);
+    }
+  }
+
+  // Column where the hex pair for the `i`-th byte of a row starts:
+  // an 8-digit offset, 2 spaces, then 16 "XX " columns grouped 8+8
+  // with an extra space at the halfway point.
+  fn hex_col_for_byte(i: usize) -> usize {
+    10 + i * 3 + if i >= 8 { 1 } else { 0 }
+  }
+
+  fn hex_ascii_start_col() -> usize {
+    Self::hex_col_for_byte(HexDocument::BYTES_PER_ROW) + 2
+  }
+
+  fn scroll_hex(&mut self, doc: &HexDocument) {
+    let row = (doc.cursor / HexDocument::BYTES_PER_ROW) as i32;
+    let text_height = self.text_height() as i32;
+
+    if row < self.row_offset {
+      self.row_offset = row;
+    }
+    if row >= self.row_offset + text_height {
+      self.row_offset = row - text_height + 1;
+    }
+  }
+
+  fn hex_cursor_position(&self, doc: &HexDocument) -> (i32, i32) {
+    let row = (doc.cursor / HexDocument::BYTES_PER_ROW) as i32;
+    let col_in_row = doc.cursor % HexDocument::BYTES_PER_ROW;
+
+    let screen_col = match doc.pane {
+      HexPane::Hex   => Self::hex_col_for_byte(col_in_row) + doc.nibble as usize,
+      HexPane::Ascii => Self::hex_ascii_start_col() + col_in_row
+    };
+
+    (row - self.row_offset, screen_col as i32)
+  }
+
+  fn refresh_hex(&mut self, doc: &HexDocument, status: StatusInfo, message: Option<&str>) -> io::Result<()> {
+    self.scroll_hex(doc);
+
+    let mut buffer = AnsiBuffer::new();
+    buffer.hide_cursor();
+    buffer.move_top_left();
+    self.draw_hex_rows(&mut buffer, doc);
+    self.draw_status_bar(&mut buffer, &status);
+    self.draw_message_bar(&mut buffer, message);
+    buffer.move_top_left();
+
+    let (screen_row, screen_col) = self.hex_cursor_position(doc);
+    buffer.move_cursor_to(screen_row, screen_col);
+    buffer.show_cursor();
+    buffer.emit_and_flush(&mut self.stdout)
+  }
+
+  fn draw_hex_rows(&mut self, buffer: &mut AnsiBuffer, doc: &HexDocument) {
+    for visual_row in 0..self.text_height() {
+      self.draw_hex_row(buffer, doc, visual_row);
+      buffer.erase_to_end_of_line();
+      buffer.append("\r\n")
+    }
+  }
+
+  fn draw_hex_row(&mut self, buffer: &mut AnsiBuffer, doc: &HexDocument, visual_row: u32) {
+    let row = visual_row as i32 + self.row_offset;
+    let row_start = row as usize * HexDocument::BYTES_PER_ROW;
+
+    if row < 0 || (row_start >= doc.bytes.len() && !(doc.bytes.is_empty() && row == 0)) {
+      return;
+    }
+
+    buffer.append(&format!("{:08x}  ", row_start));
+
+    let mut ascii = String::new();
+    for i in 0..HexDocument::BYTES_PER_ROW {
+      if i == 8 {
+        buffer.append(" ");
+      }
+
+      match doc.bytes.get(row_start + i) {
+        Some(byte) => {
+          buffer.append(&format!("{:02x} ", byte));
+          ascii.push(if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' });
+        }
+        None => {
+          buffer.append("   ");
+          ascii.push(' ');
+        }
+      }
+    }
+
+    buffer.append("  ");
+    buffer.append(&ascii);
   }
 }
 
 struct Editor {
   restore_termios: Termios,
-  keyboard: Keyboard,
+  input: InputThread,
   screen: Screen,
+  // The document's source of truth. `rows` below is a render cache
+  // kept in sync with it, since drawing/scrolling/search all already
+  // work in terms of EditorRow.
+  buffer: PieceTable,
+  rows: Vec<EditorRow>,
+  filename: Option<String>,
+  dirty: u32,
+  status_message: Option<(String, Instant)>,
+  // Some(_) while the alternate hex-dump view is active.
+  hex: Option<HexDocument>,
 }
 
 impl Editor {
+  const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
   fn new() -> io::Result<Self> {
     let original_termios = Editor::enter_raw_mode()?;
     let screen = Screen::new()?;
-    let editor =
+    let mut editor =
       Editor {
         restore_termios: original_termios,
-        keyboard: Keyboard::new(),
+        input: InputThread::spawn(),
         screen: screen,
+        buffer: PieceTable::new(Vec::new()),
+        rows: Vec::new(),
+        filename: None,
+        dirty: 0,
+        status_message: None,
+        hex: None,
       };
+    editor.set_status_message("HELP: Ctrl-Q = quit | Ctrl-H = hex mode".to_string());
     Ok(editor)
   }
 
+  fn open(&mut self, path: &str) -> io::Result<()> {
+    let bytes = fs::read(path)?;
+    self.buffer = PieceTable::new(bytes);
+    self.sync_rows_from_buffer();
+    self.filename = Some(path.to_string());
+    Ok(())
+  }
+
+  fn sync_rows_from_buffer(&mut self) {
+    self.rows = self.buffer.to_text().lines().map(EditorRow::new).collect();
+  }
+
+  fn set_status_message(&mut self, message: String) {
+    self.status_message = Some((message, Instant::now()));
+  }
+
+  fn clear_expired_message(&mut self) {
+    let expired = match &self.status_message {
+      Some((_, at)) => at.elapsed() >= Self::MESSAGE_TIMEOUT,
+      None => false
+    };
+    if expired {
+      self.status_message = None;
+    }
+  }
+
+  fn refresh(&mut self) -> io::Result<()> {
+    self.clear_expired_message();
+
+    let message = self.status_message.as_ref().map(|(text, _)| text.as_str());
+
+    if let Some(doc) = &self.hex {
+      let status = StatusInfo {
+        filename: self.filename.as_deref(),
+        total_lines: doc.row_count(),
+        current_line: doc.cursor / HexDocument::BYTES_PER_ROW + 1,
+        modified: doc.dirty > 0,
+      };
+      return self.screen.refresh_hex(doc, status, message);
+    }
+
+    let status = StatusInfo {
+      filename: self.filename.as_deref(),
+      total_lines: self.rows.len(),
+      current_line: (self.screen.cursor.0 + 1) as usize,
+      modified: self.dirty > 0,
+    };
+
+    self.screen.refresh(&self.rows, status, message)
+  }
+
+  fn row_length(&self, row: i32) -> i32 {
+    if row < 0 {
+      return 0;
+    }
+    self.rows.get(row as usize).map_or(0, |r| r.chars.chars().count() as i32)
+  }
+
   fn restore_console(&mut self) -> io::Result<()> {
     let fd = io::stdin().as_raw_fd();
     tcsetattr(fd, TCSAFLUSH, &self.restore_termios)?;
@@ -207,38 +987,333 @@ impl Editor {
     Ok(original_termios)
   }
 
-  fn handle_navigation(&mut self, key: char) {
+  fn handle_navigation(&mut self, key: EditorKey) {
+    let page_height = self.screen.text_height() as i32;
+
     match key {
-      'w' => self.screen.update_cursor_location(-1,  0),
-      's' => self.screen.update_cursor_location( 1,  0),
-      'a' => self.screen.update_cursor_location( 0, -1),
-      'd' => self.screen.update_cursor_location( 0,  1),
+      EditorKey::ArrowUp    => self.move_cursor(-1,  0),
+      EditorKey::ArrowDown  => self.move_cursor( 1,  0),
+      EditorKey::ArrowLeft  => self.move_cursor( 0, -1),
+      EditorKey::ArrowRight => self.move_cursor( 0,  1),
+      EditorKey::PageUp     => self.move_cursor(-page_height, 0),
+      EditorKey::PageDown   => self.move_cursor( page_height, 0),
+      EditorKey::Char('w')  => self.move_cursor(-1,  0),
+      EditorKey::Char('s')  => self.move_cursor( 1,  0),
+      EditorKey::Char('a')  => self.move_cursor( 0, -1),
+      EditorKey::Char('d')  => self.move_cursor( 0,  1),
       _ => ()
     }
   }
 
-  fn process_key(&mut self) -> bool {
-    let key = self.keyboard.read_key();
+  // Moves the document cursor by exactly one non-zero axis at a time
+  // (as every caller above does), clamping to the current line's
+  // length and letting a horizontal move run off the end of a line
+  // onto the next one.
+  fn move_cursor(&mut self, row_delta: i32, col_delta: i32) {
+    let (row, col) = self.screen.cursor;
+
+    let (mut row, mut col) = if col_delta < 0 {
+      if col > 0 {
+        (row, col + col_delta)
+      } else if row > 0 {
+        (row - 1, self.row_length(row - 1))
+      } else {
+        (row, col)
+      }
+    } else if col_delta > 0 {
+      if col < self.row_length(row) {
+        (row, col + col_delta)
+      } else if (row as usize) + 1 < self.rows.len() {
+        (row + 1, 0)
+      } else {
+        (row, col)
+      }
+    } else {
+      (row + row_delta, col)
+    };
+
+    row = row.clamp(0, self.rows.len() as i32);
+    col = col.clamp(0, self.row_length(row));
+
+    self.screen.cursor = (row, col);
+  }
+
+  fn process_key(&mut self, key: EditorKey) -> io::Result<bool> {
+    if let EditorKey::Char(c) = key {
+      if c == Keyboard::ctrl_key('q') {
+        return Ok(true);
+      }
+      if c == Keyboard::ctrl_key('h') {
+        self.toggle_hex_mode()?;
+        return Ok(false);
+      }
+      if c == Keyboard::ctrl_key('s') && self.hex.is_some() {
+        self.save_hex_document();
+        return Ok(false);
+      }
+      if c == Keyboard::ctrl_key('f') && self.hex.is_none() {
+        self.find()?;
+        return Ok(false);
+      }
+    }
+
+    if self.hex.is_some() {
+      self.handle_hex_key(key)?;
+      return Ok(false);
+    }
+
     self.handle_navigation(key);
 
+    Ok(false)
+  }
+
+  // Toggles the alternate hex-dump view. Entering it re-opens the
+  // current file as raw bytes (an unnamed buffer starts empty);
+  // leaving it simply drops the hex document and returns to the text
+  // view, which was left untouched the whole time.
+  fn toggle_hex_mode(&mut self) -> io::Result<()> {
+    if self.hex.is_some() {
+      self.hex = None;
+      self.set_status_message("Hex mode off".to_string());
+      return Ok(());
+    }
+
+    let doc = match &self.filename {
+      Some(path) => HexDocument::open(path)?,
+      None       => HexDocument::empty()
+    };
+
+    self.hex = Some(doc);
+    self.set_status_message("Hex mode: Tab switches panes, Ctrl-S to save, Ctrl-H to exit".to_string());
+    Ok(())
+  }
+
+  // Edits only ever touch the in-memory buffer; this is the one place
+  // a hex edit reaches disk, and only on explicit request.
+  fn save_hex_document(&mut self) {
+    let result = match &mut self.hex {
+      Some(doc) => doc.save(),
+      None      => Ok(())
+    };
+
+    match result {
+      Ok(())   => self.set_status_message("Saved to disk".to_string()),
+      Err(e)   => self.set_status_message(format!("Can't save: {}", e))
+    }
+  }
+
+  fn handle_hex_key(&mut self, key: EditorKey) -> io::Result<()> {
+    let page = self.screen.text_height() as usize * HexDocument::BYTES_PER_ROW;
+
+    let doc = match &mut self.hex {
+      Some(doc) => doc,
+      None      => return Ok(())
+    };
+
     match key {
-      c if c == Keyboard::ctrl_key('q') => true,
-      c => { print!("{}", c); false }
+      EditorKey::ArrowLeft => {
+        if doc.cursor > 0 {
+          doc.cursor -= 1;
+        }
+        doc.nibble = 0;
+      }
+      EditorKey::ArrowRight => {
+        if !doc.bytes.is_empty() && doc.cursor + 1 < doc.bytes.len() {
+          doc.cursor += 1;
+        }
+        doc.nibble = 0;
+      }
+      EditorKey::ArrowUp => {
+        doc.cursor = doc.cursor.saturating_sub(HexDocument::BYTES_PER_ROW);
+      }
+      EditorKey::ArrowDown => {
+        let next = doc.cursor + HexDocument::BYTES_PER_ROW;
+        if !doc.bytes.is_empty() && next < doc.bytes.len() {
+          doc.cursor = next;
+        }
+      }
+      EditorKey::PageUp => {
+        doc.cursor = doc.cursor.saturating_sub(page);
+      }
+      EditorKey::PageDown if !doc.bytes.is_empty() => {
+        doc.cursor = (doc.cursor + page).min(doc.bytes.len() - 1);
+      }
+      EditorKey::Delete => {
+        doc.delete_byte(doc.cursor);
+        doc.nibble = 0;
+      }
+      EditorKey::Char('\t') => {
+        doc.pane = match doc.pane {
+          HexPane::Hex   => HexPane::Ascii,
+          HexPane::Ascii => HexPane::Hex
+        };
+        doc.nibble = 0;
+      }
+      EditorKey::Char(c) if doc.pane == HexPane::Hex && c.is_ascii_hexdigit() => {
+        if doc.bytes.is_empty() {
+          doc.insert_byte(0, 0);
+        }
+
+        let digit = c.to_digit(16).unwrap() as u8;
+        doc.set_nibble(doc.cursor, doc.nibble == 0, digit);
+
+        if doc.nibble == 0 {
+          doc.nibble = 1;
+        } else {
+          doc.nibble = 0;
+          if doc.cursor + 1 < doc.bytes.len() {
+            doc.cursor += 1;
+          }
+        }
+      }
+      EditorKey::Char(c) if doc.pane == HexPane::Ascii && !c.is_control() => {
+        if doc.bytes.is_empty() {
+          doc.insert_byte(0, 0);
+        }
+
+        doc.update_byte(doc.cursor, c as u8);
+        if doc.cursor + 1 < doc.bytes.len() {
+          doc.cursor += 1;
+        }
+      }
+      _ => ()
     }
+
+    Ok(())
   }
 
   fn run_loop(&mut self) -> io::Result<()> {
+    // Refreshing on a short timeout rather than blocking on the next
+    // keystroke is what lets the message bar expire on its own and
+    // keeps the door open for other timed/async work later.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    let result = loop {
+      self.refresh()?;
+
+      if let Some(key) = self.input.recv_timeout(POLL_INTERVAL) {
+        match self.process_key(key) {
+          Ok(true)  => break Ok(()),
+          Ok(false) => (),
+          Err(e)    => break Err(e)
+        }
+      }
+    };
+
+    self.input.shutdown();
+    result
+  }
+
+  // Prompts for a search query in the message bar, incrementally
+  // jumping to and highlighting the first match on every keystroke.
+  // Arrow Left/Up steps to the previous match, Arrow Right/Down to the
+  // next; Escape restores the cursor and scroll position from before
+  // the search began, Enter leaves the cursor at the match.
+  fn find(&mut self) -> io::Result<()> {
+    let saved_cursor = self.screen.cursor;
+    let saved_row_offset = self.screen.row_offset;
+    let saved_col_offset = self.screen.col_offset;
+
+    let mut query = String::new();
+    let mut last_match: Option<i32> = None;
+    let mut direction = 1;
+    let mut confirmed = false;
+
     loop {
-      self.screen.refresh()?;
-      if self.process_key() {
-        break Ok(())
+      self.set_status_message(format!("Search: {} (Use ESC/Arrows/Enter)", query));
+      self.refresh()?;
+
+      let key = match self.input.recv() {
+        Some(key) => key,
+        None       => break
+      };
+
+      match key {
+        EditorKey::Escape => break,
+        EditorKey::Char('\r') => { confirmed = true; break; }
+        EditorKey::Char(c) if c == Keyboard::ctrl_key('h') || c == '\x7f' => {
+          query.pop();
+          last_match = None;
+          direction = 1;
+        }
+        EditorKey::ArrowLeft | EditorKey::ArrowUp => direction = -1,
+        EditorKey::ArrowRight | EditorKey::ArrowDown => direction = 1,
+        EditorKey::Char(c) if !c.is_control() => {
+          query.push(c);
+          last_match = None;
+          direction = 1;
+        }
+        _ => ()
       }
+
+      self.search_step(&query, &mut last_match, direction);
     }
+
+    self.screen.highlight = None;
+    self.status_message = None;
+
+    if !confirmed {
+      self.screen.cursor = saved_cursor;
+      self.screen.row_offset = saved_row_offset;
+      self.screen.col_offset = saved_col_offset;
+    }
+
+    Ok(())
+  }
+
+  // Scans forward or backward from `last_match` for `query`, wrapping
+  // around the file, and moves the cursor and highlight to the hit.
+  fn search_step(&mut self, query: &str, last_match: &mut Option<i32>, direction: i32) {
+    if self.rows.is_empty() || query.is_empty() {
+      self.screen.highlight = None;
+      return;
+    }
+
+    let total = self.rows.len() as i32;
+    let mut current = last_match.unwrap_or(-1);
+
+    for _ in 0..total {
+      current += direction;
+      if current < 0 {
+        current = total - 1;
+      }
+      if current >= total {
+        current = 0;
+      }
+
+      let row = &self.rows[current as usize];
+      if let Some(byte_idx) = row.render.find(query) {
+        let start_char = row.render[..byte_idx].chars().count();
+        let end_char = start_char + query.chars().count();
+
+        *last_match = Some(current);
+        self.screen.cursor.0 = current;
+        self.screen.cursor.1 = EditorRow::render_x_to_chars_x(&row.chars, start_char) as i32;
+        self.screen.row_offset = total;
+        self.screen.highlight = Some((current, start_char, end_char));
+        return;
+      }
+    }
+
+    self.screen.highlight = None;
+  }
+}
+
+// Guarantees the terminal is restored out of raw mode on every exit
+// path -- an early `?` from `open` or `run_loop`, or a panic -- not
+// just the happy path through `main`.
+impl Drop for Editor {
+  fn drop(&mut self) {
+    let _ = self.restore_console();
   }
 }
 
 fn main() -> io::Result<()> {
   let mut editor = Editor::new()?;
-  editor.run_loop()?;
-  editor.restore_console()
+
+  if let Some(path) = env::args().nth(1) {
+    editor.open(&path)?;
+  }
+
+  editor.run_loop()
 }